@@ -1,20 +1,18 @@
 mod motor;
+mod mqtt;
+mod ramp;
+mod scpi;
+mod udp;
+mod wifi;
 
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use embedded_svc::{
-    http::Method,
-    io::Write,
-    wifi::{self, AccessPointConfiguration, AuthMethod},
-    ws::FrameType,
-};
+use embedded_svc::{http::Method, io::Write, ws::FrameType};
 
 use esp_idf_svc::{
-    eventloop::EspSystemEventLoop,
-    hal::gpio::*,
-    http::server::EspHttpServer,
+    eventloop::EspSystemEventLoop, hal::gpio::*, http::server::EspHttpServer,
     nvs::EspDefaultNvsPartition,
-    wifi::{BlockingWifi, EspWifi},
 };
 use esp_idf_svc::{
     hal::{
@@ -28,10 +26,8 @@ use esp_idf_svc::{
 
 use log::*;
 
-use crate::motor::{Direction, MotorControl};
+use crate::motor::MotorControl;
 
-const SSID: &str = env!("WIFI_SSID");
-const PASSWORD: &str = env!("WIFI_PASS");
 static INDEX_HTML: &str = include_str!("page.html");
 
 // Max payload length
@@ -40,8 +36,12 @@ const MAX_LEN: usize = 128;
 // Need lots of stack to parse JSON
 const STACK_SIZE: usize = 10240;
 
-// Wi-Fi channel, between 1 and 11
-const CHANNEL: u8 = 11;
+// Stack for the background ramp task; it only does float arithmetic and a
+// mutex lock, so it needs far less than the HTTP/MQTT stacks.
+const RAMP_STACK_SIZE: usize = 4096;
+
+// How often the acceleration ramp is advanced.
+const RAMP_TICK_INTERVAL: Duration = Duration::from_millis(5);
 
 fn main() -> anyhow::Result<()> {
     esp_idf_svc::sys::link_patches();
@@ -50,52 +50,59 @@ fn main() -> anyhow::Result<()> {
     let peripherals = Peripherals::take()?;
     let pins = peripherals.pins;
 
-    let timer_driver = Arc::new(LedcTimerDriver::new(
+    // Each wheel gets its own LEDC timer so the two channels' step rates can
+    // be programmed independently (needed for differential-drive velocity
+    // commands).
+    let left_timer = Arc::new(LedcTimerDriver::new(
         peripherals.ledc.timer0,
         &TimerConfig::default().frequency(100u32.Hz()),
     )?);
+    let right_timer = Arc::new(LedcTimerDriver::new(
+        peripherals.ledc.timer1,
+        &TimerConfig::default().frequency(100u32.Hz()),
+    )?);
 
     let mut left_motor =
-        LedcDriver::new(peripherals.ledc.channel0, timer_driver.clone(), pins.gpio5)?;
+        LedcDriver::new(peripherals.ledc.channel0, left_timer.clone(), pins.gpio5)?;
 
-    let mut right_motor = LedcDriver::new(peripherals.ledc.channel1, timer_driver, pins.gpio6)?;
+    let mut right_motor =
+        LedcDriver::new(peripherals.ledc.channel1, right_timer.clone(), pins.gpio6)?;
 
     left_motor.set_duty(left_motor.get_max_duty() / 2)?;
     right_motor.set_duty(right_motor.get_max_duty() / 2)?;
     left_motor.disable()?;
     right_motor.disable()?;
 
-    let motor_control = Mutex::new(MotorControl {
-        left_step: left_motor,
-        left_dir: PinDriver::output(pins.gpio20)?,
-        right_step: right_motor,
-        right_dir: PinDriver::output(pins.gpio21)?,
-    });
+    let motor_control = Arc::new(Mutex::new(MotorControl::new(
+        left_motor,
+        PinDriver::output(pins.gpio20)?,
+        right_motor,
+        PinDriver::output(pins.gpio21)?,
+        left_timer,
+        right_timer,
+    )));
+
+    let ramp_motor_control = motor_control.clone();
+    std::thread::Builder::new()
+        .stack_size(RAMP_STACK_SIZE)
+        .spawn(move || loop {
+            let mut motor_control = ramp_motor_control
+                .lock()
+                .expect("Could not lock motor_control");
+
+            if let Err(e) = motor_control.tick() {
+                error!("Could not advance speed ramp: {:?}", e);
+            }
+
+            drop(motor_control);
+
+            std::thread::sleep(RAMP_TICK_INTERVAL);
+        })?;
 
     let sys_loop = EspSystemEventLoop::take()?;
     let nvs = EspDefaultNvsPartition::take()?;
 
-    let mut wifi = BlockingWifi::wrap(
-        EspWifi::new(peripherals.modem, sys_loop.clone(), Some(nvs))?,
-        sys_loop,
-    )?;
-
-    let wifi_configuration = wifi::Configuration::AccessPoint(AccessPointConfiguration {
-        ssid: SSID.try_into().unwrap(),
-        ssid_hidden: false,
-        auth_method: AuthMethod::WPA2Personal,
-        password: PASSWORD.try_into().unwrap(),
-        channel: CHANNEL,
-        ..Default::default()
-    });
-    wifi.set_configuration(&wifi_configuration)?;
-    wifi.start()?;
-    wifi.wait_netif_up()?;
-
-    info!(
-        "Created Wi-Fi with WIFI_SSID `{}` and WIFI_PASS `{}`",
-        SSID, PASSWORD
-    );
+    let wifi = wifi::connect(peripherals.modem, sys_loop, nvs.clone())?;
 
     let server_configuration = esp_idf_svc::http::server::Configuration {
         stack_size: STACK_SIZE,
@@ -109,6 +116,10 @@ fn main() -> anyhow::Result<()> {
     // https://doc.rust-lang.org/stable/core/mem/fn.forget.html
     core::mem::forget(wifi);
 
+    let mqtt_client = mqtt::start(motor_control.clone())?;
+
+    udp::start(motor_control.clone(), mqtt_client.clone())?;
+
     let mut server = EspHttpServer::new(&server_configuration)?;
 
     server.fn_handler("/", Method::Get, |req| {
@@ -117,6 +128,9 @@ fn main() -> anyhow::Result<()> {
             .map(|_| ())
     })?;
 
+    wifi::register_provisioning_handler(&mut server, nvs)?;
+
+    let ws_motor_control = motor_control.clone();
     server.ws_handler("/ws/control", move |ws| {
         if ws.is_new() {
             info!("New WebSocket session {}", ws.session());
@@ -147,41 +161,21 @@ fn main() -> anyhow::Result<()> {
 
         info!("Received command {}", command);
 
-        let mut command = command.split('-');
+        let mut motor_control = ws_motor_control
+            .lock()
+            .expect("Could not lock motor_control");
 
-        let mut motor_control = motor_control.lock().expect("Could not lock motor_control");
-
-        let direction = if let Some(cmd_dir) = command.next() {
-            match cmd_dir {
-                "fwd" => Some(Direction::Forward),
-                "back" => Some(Direction::Back),
-                "left" => Some(Direction::Left),
-                "right" => Some(Direction::Right),
-                _ => {
-                    error!("Invalid command received {}", cmd_dir);
-                    None
-                }
+        let response = match mqtt::handle_command(&mqtt_client, &mut motor_control, command) {
+            Ok(response) => response.to_frame(),
+            Err(e) => {
+                error!("Command error: {}", e);
+                format!("ERR:{}", e)
             }
-        } else {
-            error!("Did not get a direction");
-            None
         };
 
-        if let Some(direction) = direction {
-            motor_control
-                .set_direction(direction)
-                .expect("Could not set direction");
-        }
+        drop(motor_control);
 
-        if let Some(state) = command.next() {
-            match state {
-                "down" => motor_control.set_enable(true).expect("Could not step"),
-                "up" => motor_control.set_enable(false).expect("Could not step"),
-                _ => {
-                    error!("Invalid button state {}", state);
-                }
-            }
-        }
+        ws.send(FrameType::Text(false), response.as_bytes())?;
 
         Ok(())
     })?;
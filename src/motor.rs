@@ -1,16 +1,39 @@
+use std::sync::Arc;
+
 use esp_idf_svc::{
-    hal::{gpio::*, ledc::LedcDriver},
+    hal::{
+        gpio::*,
+        ledc::{LedcDriver, LedcTimerDriver},
+        units::FromValueType,
+    },
     sys::EspError,
 };
 
+use crate::ramp::{self, Ramp};
+
+// Distance between the left and right wheels in meters, used to split a
+// linear/angular velocity command into independent wheel rates. Tune to the
+// chassis.
+const WHEELBASE: f32 = 0.2;
+
 pub struct MotorControl<'a> {
     // left_step: AnyOutputPin,
     pub left_step: LedcDriver<'a>,
     pub left_dir: PinDriver<'a, Gpio20, Output>,
     pub right_step: LedcDriver<'a>,
     pub right_dir: PinDriver<'a, Gpio21, Output>,
+    left_timer: Arc<LedcTimerDriver<'a>>,
+    right_timer: Arc<LedcTimerDriver<'a>>,
+    direction: Direction,
+    enabled: bool,
+    ramp: Ramp,
+    // Direction to switch to once the ramp has decelerated to a stop, and
+    // the rate to resume ramping towards once it's applied.
+    pending_direction: Option<Direction>,
+    resume_rate: f32,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Forward,
     Back,
@@ -18,7 +41,54 @@ pub enum Direction {
     Right,
 }
 
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Forward => "fwd",
+            Direction::Back => "back",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        }
+    }
+}
+
 impl<'a> MotorControl<'a> {
+    /// Wraps the step/dir pin drivers, starting out disabled and facing
+    /// forward until a direction is explicitly set. Each wheel has its own
+    /// LEDC timer so `set_velocity` can drive them at independent rates;
+    /// `set_speed` reprograms both together for the plain Direction-based
+    /// commands.
+    pub fn new(
+        left_step: LedcDriver<'a>,
+        left_dir: PinDriver<'a, Gpio20, Output>,
+        right_step: LedcDriver<'a>,
+        right_dir: PinDriver<'a, Gpio21, Output>,
+        left_timer: Arc<LedcTimerDriver<'a>>,
+        right_timer: Arc<LedcTimerDriver<'a>>,
+    ) -> Self {
+        let mut motor_control = Self {
+            left_step,
+            left_dir,
+            right_step,
+            right_dir,
+            left_timer,
+            right_timer,
+            direction: Direction::Forward,
+            enabled: false,
+            ramp: Ramp::new(),
+            pending_direction: None,
+            resume_rate: 0.0,
+        };
+
+        // Drive the dir pins to match `direction`, since `set_direction`
+        // skips a direction that is already current.
+        motor_control
+            .apply_direction(Direction::Forward)
+            .expect("Could not set initial motor direction");
+
+        motor_control
+    }
+
     pub fn set_enable(&mut self, enable: bool) -> Result<(), EspError> {
         if enable {
             self.left_step.enable()?;
@@ -28,10 +98,45 @@ impl<'a> MotorControl<'a> {
             self.right_step.disable()?;
         }
 
+        self.enabled = enable;
+
         Ok(())
     }
 
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Sets the direction, decelerating to a stop first if the motors are
+    /// currently moving so the dir pins never flip out from under a moving
+    /// stepper. Once stopped, `tick` applies the flip and ramps back up to
+    /// the rate the ramp was at when the change was requested; a higher
+    /// target it was still accelerating towards is not resumed.
+    ///
+    /// Whether the motors are moving is read off `self.enabled` rather than
+    /// `self.ramp.current_rate()`, since that's what directly gates the step
+    /// pulses regardless of whether the rate got there via the ramp or via
+    /// `set_velocity` driving the timers directly.
     pub fn set_direction(&mut self, direction: Direction) -> Result<(), EspError> {
+        if direction == self.direction {
+            return Ok(());
+        }
+
+        if self.enabled {
+            self.resume_rate = self.ramp.current_rate();
+            self.pending_direction = Some(direction);
+            self.ramp.set_target(0.0);
+            return Ok(());
+        }
+
+        self.apply_direction(direction)
+    }
+
+    fn apply_direction(&mut self, direction: Direction) -> Result<(), EspError> {
         match direction {
             Direction::Forward => {
                 self.left_dir.set_high()?;
@@ -51,6 +156,112 @@ impl<'a> MotorControl<'a> {
             }
         }
 
+        self.direction = direction;
+
         Ok(())
     }
+
+    /// Immediately reprograms both wheels' LEDC timers to step at
+    /// `steps_per_sec`, changing the speed of both wheels together. Prefer
+    /// `set_target_speed` plus `tick` for a smooth ramp; this is the raw
+    /// setter the ramp itself pushes values through.
+    pub fn set_speed(&mut self, steps_per_sec: u32) -> Result<(), EspError> {
+        let steps_per_sec =
+            steps_per_sec.clamp(ramp::MIN_STEP_RATE as u32, ramp::MAX_STEP_RATE as u32);
+        let hz = steps_per_sec.Hz();
+
+        self.left_timer.set_frequency(hz)?;
+        self.right_timer.set_frequency(hz)?;
+
+        Ok(())
+    }
+
+    /// Maps a (forward speed, turn rate) command to independent left/right
+    /// wheel rates via a differential-drive model and drives each wheel's
+    /// dir pin and LEDC timer directly from the result, bypassing the
+    /// acceleration ramp used for `set_direction`/`set_target_speed`. Passing
+    /// zero for both stops the robot.
+    pub fn set_velocity(&mut self, linear: f32, angular: f32) -> Result<(), EspError> {
+        // A pending direction flip from `set_direction` would otherwise land
+        // later and stomp on the dir pins this sets directly.
+        self.pending_direction = None;
+
+        let v_left = linear - angular * WHEELBASE / 2.0;
+        let v_right = linear + angular * WHEELBASE / 2.0;
+
+        if v_left.abs() < ramp::MIN_STEP_RATE && v_right.abs() < ramp::MIN_STEP_RATE {
+            self.resume_rate = 0.0;
+            self.ramp.reset();
+            return self.set_enable(false);
+        }
+
+        // Mirrors `apply_direction`'s pin patterns, so `direction()`/MQTT
+        // telemetry reflects which way the robot is actually turning instead
+        // of going stale after the first velocity command.
+        self.direction = match (v_left >= 0.0, v_right >= 0.0) {
+            (true, true) => Direction::Forward,
+            (false, false) => Direction::Back,
+            (false, true) => Direction::Left,
+            (true, false) => Direction::Right,
+        };
+
+        if v_left >= 0.0 {
+            self.left_dir.set_high()?;
+        } else {
+            self.left_dir.set_low()?;
+        }
+        let left_rate =
+            (v_left.abs() as u32).clamp(ramp::MIN_STEP_RATE as u32, ramp::MAX_STEP_RATE as u32);
+        self.left_timer.set_frequency(left_rate.Hz())?;
+
+        if v_right >= 0.0 {
+            self.right_dir.set_high()?;
+        } else {
+            self.right_dir.set_low()?;
+        }
+        let right_rate =
+            (v_right.abs() as u32).clamp(ramp::MIN_STEP_RATE as u32, ramp::MAX_STEP_RATE as u32);
+        self.right_timer.set_frequency(right_rate.Hz())?;
+
+        // Seed the ramp with the (averaged) rate actually applied, so a
+        // later `set_direction` decelerates from here instead of treating
+        // the motors as already stopped and flipping the dir pins at speed.
+        let applied_rate = (left_rate + right_rate) as f32 / 2.0;
+        self.resume_rate = applied_rate;
+        self.ramp.resume_at(applied_rate);
+
+        self.set_enable(true)
+    }
+
+    /// Sets the step rate to ramp towards instead of jumping to directly.
+    /// Call `tick` periodically (every few ms) from a background task to
+    /// actually advance towards it.
+    pub fn set_target_speed(&mut self, steps_per_sec: u32) {
+        self.ramp.set_target(steps_per_sec as f32);
+    }
+
+    /// Advances the acceleration ramp by one tick and, if the rate changed,
+    /// pushes it to the LEDC timers. A settled ramp pushes nothing, so the
+    /// independent wheel rates set by `set_velocity` stay in place. If a
+    /// direction change is pending, it is applied once the ramp has
+    /// decelerated to a stop, and ramping resumes towards `resume_rate`.
+    pub fn tick(&mut self) -> Result<(), EspError> {
+        let previous_rate = self.ramp.current_rate();
+        let rate = self.ramp.tick();
+
+        if rate <= 0.0 {
+            if let Some(direction) = self.pending_direction.take() {
+                self.apply_direction(direction)?;
+                self.ramp.set_target(self.resume_rate);
+            }
+
+            return Ok(());
+        }
+
+        if rate == previous_rate {
+            return Ok(());
+        }
+
+        self.set_speed(rate as u32)
+    }
 }
@@ -0,0 +1,156 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use embedded_svc::mqtt::client::QoS;
+
+use esp_idf_svc::mqtt::client::{EspMqttClient, EventPayload, MqttClientConfiguration};
+use esp_idf_svc::sntp::{EspSntp, SyncStatus};
+
+use log::*;
+
+use crate::motor::MotorControl;
+use crate::scpi::{self, CommandError, Response};
+
+const MQTT_URL: &str = env!("MQTT_URL");
+const MQTT_USER: &str = env!("MQTT_USER");
+const MQTT_PASS: &str = env!("MQTT_PASS");
+
+const CMD_TOPIC: &str = "robot/cmd";
+const STATE_TOPIC: &str = "robot/state";
+
+/// Shared handle to the MQTT client, filled in once the background thread
+/// spawned by `start` has synced time and connected to the broker. `None`
+/// until then (or forever, if the broker is never reachable); callers that
+/// need to publish should treat that as "nothing to publish to yet" rather
+/// than an error.
+pub type MqttClientHandle = Arc<Mutex<Option<EspMqttClient<'static>>>>;
+
+/// Blocks until the system clock has been synced over SNTP, then leaks the
+/// `EspSntp` handle so it keeps running for the lifetime of the program.
+fn wait_for_sntp_sync() -> anyhow::Result<()> {
+    let sntp = EspSntp::new_default()?;
+
+    info!("Waiting for SNTP time sync...");
+    while sntp.get_sync_status() != SyncStatus::Completed {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    info!("SNTP time sync complete");
+
+    core::mem::forget(sntp);
+
+    Ok(())
+}
+
+/// Spawns a background thread that waits for SNTP sync, connects to the MQTT
+/// broker, subscribes to `robot/cmd` and runs any SCPI command payload
+/// received on it against `motor_control`, the same shared state the
+/// `/ws/control` handler writes to. Returns immediately with a handle that
+/// the thread fills in once connected, so a broker (or SNTP server) that
+/// never becomes reachable - e.g. because Wi-Fi fell back to AP mode - can't
+/// block the rest of boot, only delay MQTT itself coming up.
+pub fn start(motor_control: Arc<Mutex<MotorControl<'static>>>) -> anyhow::Result<MqttClientHandle> {
+    let client_handle: MqttClientHandle = Arc::new(Mutex::new(None));
+    let thread_client_handle = client_handle.clone();
+
+    std::thread::Builder::new()
+        .stack_size(crate::STACK_SIZE)
+        .spawn(move || {
+            if let Err(e) = wait_for_sntp_sync() {
+                error!(
+                    "Could not sync time over SNTP, MQTT will not start: {:?}",
+                    e
+                );
+                return;
+            }
+
+            let mqtt_config = MqttClientConfiguration {
+                username: Some(MQTT_USER),
+                password: Some(MQTT_PASS),
+                ..Default::default()
+            };
+
+            let (mut client, mut connection) = match EspMqttClient::new(MQTT_URL, &mqtt_config) {
+                Ok(result) => result,
+                Err(e) => {
+                    error!("Could not connect to MQTT broker: {:?}", e);
+                    return;
+                }
+            };
+
+            if let Err(e) = client.subscribe(CMD_TOPIC, QoS::AtLeastOnce) {
+                error!("Could not subscribe to {}: {:?}", CMD_TOPIC, e);
+                return;
+            }
+
+            *thread_client_handle
+                .lock()
+                .expect("Could not lock mqtt client") = Some(client);
+
+            while let Ok(event) = connection.next() {
+                let EventPayload::Received { data, .. } = event.payload() else {
+                    continue;
+                };
+
+                let Ok(command) = std::str::from_utf8(data) else {
+                    error!("Could not parse MQTT payload as UTF-8");
+                    continue;
+                };
+
+                info!("Received MQTT command {}", command);
+
+                let mut motor_control = motor_control.lock().expect("Could not lock motor_control");
+                if let Err(e) = handle_command(&thread_client_handle, &mut motor_control, command) {
+                    error!("Command error: {}", e);
+                }
+            }
+        })?;
+
+    Ok(client_handle)
+}
+
+/// Runs an SCPI command against `motor_control` and publishes the resulting
+/// direction/enabled state to `robot/state` afterward, so every transport
+/// (WebSocket, UDP, MQTT itself) keeps telemetry in sync the same way
+/// instead of each reimplementing the publish.
+pub fn handle_command(
+    client: &MqttClientHandle,
+    motor_control: &mut MotorControl,
+    command: &str,
+) -> Result<Response, CommandError> {
+    let result = scpi::execute(motor_control, command);
+
+    if let Err(e) = publish_state(
+        client,
+        motor_control.direction().as_str(),
+        motor_control.is_enabled(),
+    ) {
+        error!("Could not publish MQTT telemetry: {:?}", e);
+    }
+
+    result
+}
+
+/// Publishes the current direction and enabled state to `robot/state`,
+/// stamped with the Unix time SNTP synced us to. A no-op if the MQTT client
+/// hasn't connected yet.
+pub fn publish_state(
+    client: &MqttClientHandle,
+    direction: &str,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    let mut client = client.lock().expect("Could not lock mqtt client");
+    let Some(client) = client.as_mut() else {
+        return Ok(());
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let payload = format!("dir={},enabled={},ts={}", direction, enabled, timestamp);
+
+    client.publish(STATE_TOPIC, QoS::AtMostOnce, false, payload.as_bytes())?;
+
+    Ok(())
+}
@@ -0,0 +1,229 @@
+/// Minimum step rate a stepper is driven at once enabled. Below this the
+/// motor has no usable torque, so there is no point ramping through it.
+pub const MIN_STEP_RATE: f32 = 50.0;
+
+/// Maximum step rate, above which these motors skip steps.
+pub const MAX_STEP_RATE: f32 = 4000.0;
+
+/// Tracks the inter-step interval as a target step rate is approached,
+/// following the real-time stepper acceleration profile: starting from
+/// `c0 = 1/f0`, each tick shrinks (or, mirrored, grows) the interval via
+/// `c_n = c_{n-1} - 2*c_{n-1}/(4n+1)` instead of jumping straight to the
+/// target rate.
+#[derive(Clone, Copy)]
+pub struct Ramp {
+    interval: f32,
+    step: u32,
+    current_rate: f32,
+    target_rate: f32,
+}
+
+impl Ramp {
+    pub fn new() -> Self {
+        Self {
+            interval: 1.0 / MIN_STEP_RATE,
+            step: 0,
+            current_rate: 0.0,
+            target_rate: 0.0,
+        }
+    }
+
+    pub fn current_rate(&self) -> f32 {
+        self.current_rate
+    }
+
+    /// Sets the rate this ramp accelerates or decelerates towards. Zero is
+    /// allowed (it means "stop"), but anything in between is clamped up to
+    /// `MIN_STEP_RATE` to match `set_speed`'s immediate setter - otherwise
+    /// `current_rate` floors at `MIN_STEP_RATE` and the ramp never settles.
+    pub fn set_target(&mut self, target_rate: f32) {
+        self.target_rate = if target_rate <= 0.0 {
+            0.0
+        } else {
+            target_rate.clamp(MIN_STEP_RATE, MAX_STEP_RATE)
+        };
+    }
+
+    pub fn is_settled(&self) -> bool {
+        (self.current_rate - self.target_rate).abs() < 1.0
+    }
+
+    /// Immediately settles the ramp at a stop, as if it had decelerated all
+    /// the way down, with no further `tick` advancing it. For callers that
+    /// take over step-rate control directly and need the ramp to stay out of
+    /// the way rather than gradually converging.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Seeds the ramp as if it had already settled at `rate`, rather than at
+    /// zero. For callers that drive the motors directly, bypassing `tick`,
+    /// but want a later `set_target` to accelerate or decelerate from the
+    /// real rate the hardware is at instead of from a stop.
+    pub fn resume_at(&mut self, rate: f32) {
+        let rate = rate.clamp(0.0, MAX_STEP_RATE);
+
+        self.current_rate = rate;
+        self.target_rate = rate;
+        self.step = 0;
+        self.interval = if rate > 0.0 {
+            1.0 / rate
+        } else {
+            1.0 / MIN_STEP_RATE
+        };
+    }
+
+    /// Advances the ramp by one tick and returns the resulting rate in Hz.
+    /// Meant to be called from a fixed-period background task every few ms.
+    pub fn tick(&mut self) -> f32 {
+        if self.is_settled() {
+            self.current_rate = self.target_rate;
+            self.step = 0;
+            return self.current_rate;
+        }
+
+        self.step += 1;
+        let n = self.step as f32;
+
+        self.interval = if self.target_rate > self.current_rate {
+            self.interval - (2.0 * self.interval) / (4.0 * n + 1.0)
+        } else {
+            self.interval + (2.0 * self.interval) / (4.0 * n - 1.0)
+        };
+
+        let min_interval = 1.0 / MAX_STEP_RATE;
+        let max_interval = 1.0 / MIN_STEP_RATE;
+        self.interval = self.interval.clamp(min_interval, max_interval);
+
+        self.current_rate = (1.0 / self.interval).clamp(0.0, MAX_STEP_RATE);
+
+        if self.target_rate == 0.0 && self.current_rate <= MIN_STEP_RATE {
+            self.current_rate = 0.0;
+        }
+
+        self.current_rate
+    }
+}
+
+impl Default for Ramp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_target_clamps_nonzero_targets_to_min_step_rate() {
+        let mut ramp = Ramp::new();
+
+        ramp.set_target(1.0);
+        assert_eq!(ramp.target_rate, MIN_STEP_RATE);
+    }
+
+    #[test]
+    fn set_target_zero_means_stop() {
+        let mut ramp = Ramp::new();
+
+        ramp.set_target(1000.0);
+        ramp.set_target(0.0);
+        assert_eq!(ramp.target_rate, 0.0);
+    }
+
+    #[test]
+    fn set_target_clamps_above_max_step_rate() {
+        let mut ramp = Ramp::new();
+
+        ramp.set_target(MAX_STEP_RATE + 1000.0);
+        assert_eq!(ramp.target_rate, MAX_STEP_RATE);
+    }
+
+    #[test]
+    fn is_settled_true_only_within_one_hz_of_target() {
+        let mut ramp = Ramp::new();
+        ramp.set_target(500.0);
+
+        assert!(!ramp.is_settled());
+
+        ramp.current_rate = 500.0;
+        assert!(ramp.is_settled());
+
+        ramp.current_rate = 499.5;
+        assert!(ramp.is_settled());
+    }
+
+    #[test]
+    fn reset_settles_at_a_stop() {
+        let mut ramp = Ramp::new();
+        ramp.set_target(1000.0);
+        for _ in 0..50 {
+            ramp.tick();
+        }
+        assert!(ramp.current_rate() > 0.0);
+
+        ramp.reset();
+
+        assert_eq!(ramp.current_rate(), 0.0);
+        assert!(ramp.is_settled());
+        assert_eq!(ramp.tick(), 0.0);
+    }
+
+    #[test]
+    fn resume_at_seeds_current_and_target_rate() {
+        let mut ramp = Ramp::new();
+
+        ramp.resume_at(800.0);
+
+        assert_eq!(ramp.current_rate(), 800.0);
+        assert!(ramp.is_settled());
+        assert_eq!(ramp.tick(), 800.0);
+    }
+
+    #[test]
+    fn ticks_accelerate_to_target_and_then_decelerate_to_zero() {
+        let mut ramp = Ramp::new();
+        ramp.set_target(1000.0);
+
+        let mut last_rate = ramp.current_rate();
+        let mut reached_target = false;
+        for _ in 0..10_000 {
+            let rate = ramp.tick();
+            assert!(
+                rate >= last_rate - f32::EPSILON,
+                "rate should never decrease while accelerating"
+            );
+            last_rate = rate;
+
+            if ramp.is_settled() {
+                reached_target = true;
+                break;
+            }
+        }
+        assert!(reached_target, "ramp never reached its accel target");
+        assert!((ramp.current_rate() - 1000.0).abs() < 1.0);
+
+        ramp.set_target(0.0);
+
+        // Convergence near the MIN_STEP_RATE floor is asymptotically slow
+        // (the Austin algorithm's decrements shrink as the step count
+        // grows), so deceleration to an exact zero takes far more ticks
+        // than the climb to the target did.
+        let mut reached_zero = false;
+        for _ in 0..200_000 {
+            let rate = ramp.tick();
+            assert!(
+                rate <= last_rate + f32::EPSILON,
+                "rate should never increase while decelerating"
+            );
+            last_rate = rate;
+
+            if rate == 0.0 {
+                reached_zero = true;
+                break;
+            }
+        }
+        assert!(reached_zero, "ramp never decelerated back to zero");
+    }
+}
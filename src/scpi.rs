@@ -0,0 +1,155 @@
+use std::fmt;
+
+use esp_idf_svc::sys::EspError;
+
+use crate::motor::{Direction, MotorControl};
+
+/// A malformed or failed command. The WebSocket handler sends it back to the
+/// client as `ERR:<message>`; the UDP and MQTT transports log it.
+#[derive(Debug)]
+pub enum CommandError {
+    UnknownCommand(String),
+    MissingArgument,
+    InvalidArgument(String),
+    Hardware(EspError),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand(command) => write!(f, "unknown command '{}'", command),
+            CommandError::MissingArgument => write!(f, "missing argument"),
+            CommandError::InvalidArgument(argument) => {
+                write!(f, "invalid argument '{}'", argument)
+            }
+            CommandError::Hardware(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<EspError> for CommandError {
+    fn from(e: EspError) -> Self {
+        CommandError::Hardware(e)
+    }
+}
+
+/// What running a command produces: a bare acknowledgement, or the answer
+/// to a query (anything ending in `?`).
+pub enum Response {
+    Ack,
+    Value(String),
+}
+
+impl Response {
+    /// Renders the response as the line sent back to the client.
+    pub fn to_frame(&self) -> String {
+        match self {
+            Response::Ack => "OK".to_string(),
+            Response::Value(value) => value.clone(),
+        }
+    }
+}
+
+/// Tokenizes and runs a single SCPI-style line against `motor_control`, e.g.
+/// `MOVE:DIR FWD`, `MOVE:VEL 400 500`, `MOTOR:ENABLE ON`, `MOTOR:SPEED 400`
+/// or the query `MOTOR:STATE?`.
+pub fn execute(motor_control: &mut MotorControl, line: &str) -> Result<Response, CommandError> {
+    let line = line.trim_matches(char::from(0)).trim();
+
+    let mut parts = line.splitn(2, char::is_whitespace);
+    let header = parts.next().unwrap_or("");
+    let argument = parts.next().map(str::trim).filter(|s| !s.is_empty());
+
+    let (header, is_query) = match header.strip_suffix('?') {
+        Some(header) => (header, true),
+        None => (header, false),
+    };
+
+    let mut path = header.split(':');
+    let root = path.next().unwrap_or("").to_ascii_uppercase();
+    let leaf = path.next().unwrap_or("").to_ascii_uppercase();
+
+    match (root.as_str(), leaf.as_str()) {
+        ("MOVE", "DIR") => {
+            let direction = parse_direction(require_argument(argument)?)?;
+            motor_control.set_direction(direction)?;
+            Ok(Response::Ack)
+        }
+        ("MOVE", "VEL") => {
+            let (linear, angular) = parse_velocity(require_argument(argument)?)?;
+            motor_control.set_velocity(linear, angular)?;
+            Ok(Response::Ack)
+        }
+        ("MOTOR", "ENABLE") => {
+            if is_query {
+                return Ok(Response::Value(
+                    on_off(motor_control.is_enabled()).to_string(),
+                ));
+            }
+
+            let enable = parse_on_off(require_argument(argument)?)?;
+            motor_control.set_enable(enable)?;
+            Ok(Response::Ack)
+        }
+        ("MOTOR", "SPEED") => {
+            let argument = require_argument(argument)?;
+            let speed: u32 = argument
+                .parse()
+                .map_err(|_| CommandError::InvalidArgument(argument.to_string()))?;
+            motor_control.set_target_speed(speed);
+            Ok(Response::Ack)
+        }
+        ("MOTOR", "STATE") if is_query => Ok(Response::Value(format!(
+            "{},{}",
+            motor_control.direction().as_str(),
+            on_off(motor_control.is_enabled())
+        ))),
+        _ => Err(CommandError::UnknownCommand(header.to_string())),
+    }
+}
+
+fn require_argument(argument: Option<&str>) -> Result<&str, CommandError> {
+    argument.ok_or(CommandError::MissingArgument)
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+fn parse_direction(argument: &str) -> Result<Direction, CommandError> {
+    match argument.to_ascii_uppercase().as_str() {
+        "FWD" => Ok(Direction::Forward),
+        "BACK" => Ok(Direction::Back),
+        "LEFT" => Ok(Direction::Left),
+        "RIGHT" => Ok(Direction::Right),
+        _ => Err(CommandError::InvalidArgument(argument.to_string())),
+    }
+}
+
+fn parse_on_off(argument: &str) -> Result<bool, CommandError> {
+    match argument.to_ascii_uppercase().as_str() {
+        "ON" => Ok(true),
+        "OFF" => Ok(false),
+        _ => Err(CommandError::InvalidArgument(argument.to_string())),
+    }
+}
+
+fn parse_velocity(argument: &str) -> Result<(f32, f32), CommandError> {
+    let mut parts = argument.split_whitespace();
+
+    let linear = require_argument(parts.next())?;
+    let angular = require_argument(parts.next())?;
+
+    let linear = linear
+        .parse()
+        .map_err(|_| CommandError::InvalidArgument(linear.to_string()))?;
+    let angular = angular
+        .parse()
+        .map_err(|_| CommandError::InvalidArgument(angular.to_string()))?;
+
+    Ok((linear, angular))
+}
@@ -0,0 +1,60 @@
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+
+use log::*;
+
+use crate::motor::MotorControl;
+use crate::mqtt::{self, MqttClientHandle};
+
+// Arbitrary fixed port the robot listens for UDP control packets on.
+const UDP_PORT: u16 = 7878;
+
+// Same command length limit as the WebSocket path.
+const MAX_LEN: usize = 128;
+
+/// Binds a UDP socket on `UDP_PORT` and runs incoming SCPI-style command
+/// packets against `motor_control` from a dedicated thread, same as the
+/// `/ws/control` WebSocket handler, publishing updated telemetry to MQTT via
+/// `mqtt_client` the same way that handler does. UDP is connectionless and
+/// unframed, trading the WebSocket path's delivery guarantees for much lower
+/// latency - a better fit for a real-time teleop link, where dropping a
+/// stale command is preferable to queuing behind one.
+pub fn start(
+    motor_control: Arc<Mutex<MotorControl<'static>>>,
+    mqtt_client: MqttClientHandle,
+) -> anyhow::Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", UDP_PORT))?;
+
+    info!("Listening for UDP control packets on port {}", UDP_PORT);
+
+    std::thread::Builder::new()
+        .stack_size(crate::STACK_SIZE)
+        .spawn(move || {
+            let mut buf = [0u8; MAX_LEN];
+
+            loop {
+                let (len, _addr) = match socket.recv_from(&mut buf) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        error!("UDP recv failed: {:?}", e);
+                        continue;
+                    }
+                };
+
+                let Ok(command) = std::str::from_utf8(&buf[..len]) else {
+                    error!("Could not parse UDP packet as UTF-8");
+                    continue;
+                };
+
+                info!("Received UDP command {}", command);
+
+                let mut motor_control = motor_control.lock().expect("Could not lock motor_control");
+
+                if let Err(e) = mqtt::handle_command(&mqtt_client, &mut motor_control, command) {
+                    error!("Command error: {}", e);
+                }
+            }
+        })?;
+
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use embedded_svc::{
+    http::Method,
+    io::{Read, Write},
+    wifi::{self, AccessPointConfiguration, AuthMethod, ClientConfiguration},
+};
+
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop,
+    hal::modem::Modem,
+    http::server::EspHttpServer,
+    nvs::{EspDefaultNvsPartition, EspNvs},
+    wifi::{BlockingWifi, EspWifi},
+};
+
+use log::*;
+
+const SSID: &str = env!("WIFI_SSID");
+const PASSWORD: &str = env!("WIFI_PASS");
+
+// Wi-Fi channel, between 1 and 11
+const CHANNEL: u8 = 11;
+
+const NVS_NAMESPACE: &str = "wifi";
+const NVS_KEY_SSID: &str = "sta_ssid";
+const NVS_KEY_PASS: &str = "sta_pass";
+
+// NVS string reads include the NUL terminator, so leave room for it after
+// the longest credential `validate_credentials` accepts.
+const MAX_CREDENTIAL_LEN: usize = MAX_PASSWORD_LEN + 1;
+const MAX_BODY_LEN: usize = 256;
+
+// Capacity of the heapless strings `ClientConfiguration`'s ssid/password
+// fields convert into (`embedded_svc::wifi::ClientConfiguration`). Longer
+// values still fit in NVS but panic the `try_into().unwrap()` conversions in
+// `connect` on the next boot, so reject them here instead.
+const MAX_SSID_LEN: usize = 32;
+const MAX_PASSWORD_LEN: usize = 64;
+
+/// Reads previously provisioned station-mode credentials out of NVS, if any
+/// have been saved via `POST /wifi`.
+fn read_credentials(nvs: &EspDefaultNvsPartition) -> anyhow::Result<Option<(String, String)>> {
+    let storage = EspNvs::new(nvs.clone(), NVS_NAMESPACE, true)?;
+
+    let mut ssid_buf = [0u8; MAX_CREDENTIAL_LEN];
+    let mut pass_buf = [0u8; MAX_CREDENTIAL_LEN];
+
+    let ssid = storage.get_str(NVS_KEY_SSID, &mut ssid_buf)?;
+    let password = storage.get_str(NVS_KEY_PASS, &mut pass_buf)?;
+
+    match (ssid, password) {
+        (Some(ssid), Some(password)) => Ok(Some((ssid.to_string(), password.to_string()))),
+        _ => Ok(None),
+    }
+}
+
+/// Writes new station-mode credentials to NVS so they survive a reboot.
+fn store_credentials(
+    nvs: &EspDefaultNvsPartition,
+    ssid: &str,
+    password: &str,
+) -> anyhow::Result<()> {
+    let mut storage = EspNvs::new(nvs.clone(), NVS_NAMESPACE, true)?;
+    storage.set_str(NVS_KEY_SSID, ssid)?;
+    storage.set_str(NVS_KEY_PASS, password)?;
+
+    Ok(())
+}
+
+/// Connects to Wi-Fi, preferring stored station-mode credentials and falling
+/// back to the robot's own `WIFI_SSID`/`WIFI_PASS` access point if none are
+/// stored or the join fails.
+pub fn connect(
+    modem: Modem,
+    sys_loop: EspSystemEventLoop,
+    nvs: EspDefaultNvsPartition,
+) -> anyhow::Result<BlockingWifi<EspWifi<'static>>> {
+    let mut wifi = BlockingWifi::wrap(
+        EspWifi::new(modem, sys_loop.clone(), Some(nvs.clone()))?,
+        sys_loop,
+    )?;
+
+    if let Some((ssid, password)) = read_credentials(&nvs)? {
+        info!(
+            "Found stored Wi-Fi credentials for SSID `{}`, trying station mode",
+            ssid
+        );
+
+        let sta_configuration = wifi::Configuration::Client(ClientConfiguration {
+            ssid: ssid.as_str().try_into().unwrap(),
+            password: password.as_str().try_into().unwrap(),
+            auth_method: AuthMethod::WPA2Personal,
+            ..Default::default()
+        });
+
+        wifi.set_configuration(&sta_configuration)?;
+        wifi.start()?;
+
+        if wifi.connect().is_ok() && wifi.wait_netif_up().is_ok() {
+            info!("Connected to `{}` in station mode", ssid);
+            return Ok(wifi);
+        }
+
+        warn!(
+            "Could not join `{}`, falling back to access point mode",
+            ssid
+        );
+        wifi.stop()?;
+    }
+
+    let ap_configuration = wifi::Configuration::AccessPoint(AccessPointConfiguration {
+        ssid: SSID.try_into().unwrap(),
+        ssid_hidden: false,
+        auth_method: AuthMethod::WPA2Personal,
+        password: PASSWORD.try_into().unwrap(),
+        channel: CHANNEL,
+        ..Default::default()
+    });
+    wifi.set_configuration(&ap_configuration)?;
+    wifi.start()?;
+    wifi.wait_netif_up()?;
+
+    info!(
+        "Created Wi-Fi access point with WIFI_SSID `{}` and WIFI_PASS `{}`",
+        SSID, PASSWORD
+    );
+
+    Ok(wifi)
+}
+
+/// Pulls a `"key": "value"` string out of a (very) small JSON body without
+/// pulling in a JSON dependency for two fields.
+fn extract_json_string(body: &str, key: &str) -> Option<String> {
+    let key_pattern = format!("\"{}\"", key);
+    let after_key = &body[body.find(&key_pattern)? + key_pattern.len()..];
+    let after_colon = after_key[after_key.find(':')? + 1..].trim_start();
+    let value = after_colon.strip_prefix('"')?;
+
+    Some(value[..value.find('"')?].to_string())
+}
+
+fn parse_credentials(body: &str) -> Option<(String, String)> {
+    let ssid = extract_json_string(body, "ssid")?;
+    let password = extract_json_string(body, "password")?;
+
+    Some((ssid, password))
+}
+
+/// Checks the credentials fit in the heapless strings `ClientConfiguration`
+/// converts them into, so a too-long value is rejected here instead of
+/// panicking inside `connect` on the next boot.
+fn validate_credentials(ssid: &str, password: &str) -> Result<(), &'static str> {
+    if ssid.len() > MAX_SSID_LEN {
+        return Err("SSID must be at most 32 bytes");
+    }
+
+    if password.len() > MAX_PASSWORD_LEN {
+        return Err("Password must be at most 64 bytes");
+    }
+
+    Ok(())
+}
+
+/// Registers `POST /wifi`, which accepts `{"ssid": ..., "password": ...}`,
+/// persists it to NVS and reboots so `connect` picks it up in station mode
+/// on the next boot.
+pub fn register_provisioning_handler(
+    server: &mut EspHttpServer,
+    nvs: EspDefaultNvsPartition,
+) -> anyhow::Result<()> {
+    server.fn_handler("/wifi", Method::Post, move |mut req| {
+        let mut body = [0u8; MAX_BODY_LEN];
+        let len = req.read(&mut body)?;
+
+        let Ok(body) = std::str::from_utf8(&body[..len]) else {
+            req.into_status_response(400)?
+                .write_all(b"Body was not valid UTF-8")?;
+            return Ok(());
+        };
+
+        let Some((ssid, password)) = parse_credentials(body) else {
+            req.into_status_response(400)?
+                .write_all(b"Expected a JSON body with ssid and password")?;
+            return Ok(());
+        };
+
+        if let Err(message) = validate_credentials(&ssid, &password) {
+            req.into_status_response(400)?
+                .write_all(message.as_bytes())?;
+            return Ok(());
+        }
+
+        store_credentials(&nvs, &ssid, &password)?;
+
+        req.into_ok_response()?
+            .write_all(b"Credentials saved, rebooting...")?;
+
+        info!(
+            "Stored new Wi-Fi credentials for SSID `{}`, rebooting",
+            ssid
+        );
+
+        unsafe { esp_idf_svc::sys::esp_restart() };
+    })?;
+
+    Ok(())
+}